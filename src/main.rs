@@ -19,11 +19,114 @@ use thiserror::Error;
 
 use sources::Resolver;
 
+mod output;
+mod registry;
 mod sources;
 
-/*
- * FIXME: What will happen if package moves from one source to another? When it gets renamed?
- */
+/// Output format for the computed diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// The default textual `Display` of each `Op`.
+    Human,
+    /// A single JSON document, for CI / review-bot integration.
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            _ => Err(anyhow!(
+                "Unknown format '{}', expected 'human' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+/// How big a jump an `Op::Update` represents.
+///
+/// Variants are ordered from least to most interesting to a reviewer, which
+/// `--level` filters against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Bump {
+    Patch,
+    Prerelease,
+    Minor,
+    /// Major is 0 and minor differs — semver-breaking for 0.x crates.
+    Breaking0x,
+    Major,
+    /// `new < old`. Always shown, regardless of `--level`.
+    Downgrade,
+}
+
+impl Bump {
+    fn classify(old: &Version, new: &Version) -> Self {
+        if new < old {
+            Bump::Downgrade
+        } else if old.major != new.major {
+            Bump::Major
+        } else if old.major == 0 && old.minor != new.minor {
+            Bump::Breaking0x
+        } else if old.minor != new.minor {
+            Bump::Minor
+        } else if old.patch != new.patch {
+            Bump::Patch
+        } else {
+            Bump::Prerelease
+        }
+    }
+
+    fn passes_level(self, level: Level) -> bool {
+        match self {
+            Bump::Downgrade => true,
+            Bump::Major | Bump::Breaking0x => level <= Level::Major,
+            Bump::Minor => level <= Level::Minor,
+            Bump::Patch | Bump::Prerelease => level <= Level::Patch,
+        }
+    }
+}
+
+impl Display for Bump {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let tag = match self {
+            Bump::Patch => "patch",
+            Bump::Prerelease => "prerelease",
+            Bump::Minor => "minor",
+            Bump::Breaking0x => "breaking-0.x",
+            Bump::Major => "major",
+            Bump::Downgrade => "downgrade",
+        };
+        write!(fmt, "{}", tag)
+    }
+}
+
+/// Minimum `Bump` severity to show with `--level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    Patch,
+    Minor,
+    Major,
+}
+
+impl std::str::FromStr for Level {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "patch" => Ok(Level::Patch),
+            "minor" => Ok(Level::Minor),
+            "major" => Ok(Level::Major),
+            _ => Err(anyhow!(
+                "Unknown level '{}', expected 'patch', 'minor' or 'major'",
+                s
+            )),
+        }
+    }
+}
 
 /// Checking what changed about dependencies between versions.
 #[derive(Debug, StructOpt)]
@@ -56,6 +159,32 @@ struct Opts {
     /// Applies only if `-m/--metadata`.
     #[structopt(short = "c", long = "changelog")]
     changelog: bool,
+
+    /// Output format: `human` for the textual diff (default), `json` for a
+    /// single machine-readable document suitable for CI / review-bot
+    /// integration.
+    #[structopt(long = "format", default_value = "human")]
+    format: Format,
+
+    /// Hide updates below this bump level (`patch`, `minor`, `major`).
+    ///
+    /// E.g. `--level minor` hides pure patch bumps. Downgrades are always
+    /// shown regardless of this setting.
+    #[structopt(long = "level", default_value = "patch")]
+    level: Level,
+
+    /// Query crates.io for yanked versions and release publisher changes.
+    ///
+    /// Reports a yanked version that still made it into the lockfile, and
+    /// when the old and new version of an update were published by
+    /// different crates.io accounts. Requires network access and degrades
+    /// to a warning when the registry can't be reached.
+    ///
+    /// With `--format human`, only takes effect together with
+    /// `-m/--metadata`, same as the other metadata checks. With `--format
+    /// json`, the findings are always included in the report.
+    #[structopt(long = "registry-check")]
+    registry_check: bool,
 }
 
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
@@ -98,10 +227,12 @@ fn packages_from_str(data: &str) -> Result<Deps, Error> {
     let mut packages = Deps::new();
 
     for pkg in lockfile.packages {
-        packages
-            .entry(pkg.name.clone())
-            .or_default()
-            .push(pkg.into());
+        let mut dep: Dep = pkg.into();
+        dep.source = dep
+            .source
+            .map(|source| sources::canonicalize_source(&source))
+            .transpose()?;
+        packages.entry(dep.name.clone()).or_default().push(dep);
     }
 
     Ok(packages)
@@ -144,12 +275,26 @@ enum Op {
     Add(Dep),
     Remove(Dep),
     Update(Dep, Dep),
+    /// Same name and version, but the source changed (eg. crates.io -> git,
+    /// or a path/vendored move).
+    Move {
+        name: Name,
+        version: Version,
+        old_source: Option<SourceId>,
+        new_source: Option<SourceId>,
+    },
 }
 
 impl Op {
-    fn print_metadata(&self, resolver: &Resolver, changelog: bool) -> Result<(), Error> {
+    fn print_metadata(
+        &self,
+        resolver: &Resolver,
+        changelog: bool,
+        registry: Option<&registry::RegistryClient>,
+    ) -> Result<(), Error> {
         match self {
-            Op::Remove(_) => (), // Removing deps is always good!
+            Op::Remove(_) => (),   // Removing deps is always good!
+            Op::Move { .. } => (), // Same package, nothing to say about build scripts/license
             Op::Add(dep) => {
                 if let Some(pkg) = resolver.pkg(dep)? {
                     if pkg.has_custom_build() {
@@ -159,10 +304,21 @@ impl Op {
                         println!("--> Is a proc macro");
                     }
                 }
+
+                if let Some(registry) = registry {
+                    if let Some(check) = registry::check_add(dep, registry) {
+                        if check.yanked {
+                            println!(
+                                "--> WARNING: {} {} is yanked but present in the lockfile",
+                                dep.name, dep.version
+                            );
+                        }
+                    }
+                }
             }
-            Op::Update(old, new) => {
-                let old = resolver.pkg(old)?;
-                let new = resolver.pkg(new)?;
+            Op::Update(old_dep, new_dep) => {
+                let old = resolver.pkg(old_dep)?;
+                let new = resolver.pkg(new_dep)?;
                 if let (Some(old), Some(new)) = (old, new) {
                     if !old.has_custom_build() && new.has_custom_build() {
                         println!("--> Adds a build script");
@@ -215,8 +371,32 @@ impl Op {
                     }
                 }
 
-                // TODO: We also want maintainers, these are not available through the manifest,
-                // but maybe through the crates.io
+                if let Some(registry) = registry {
+                    if let Some(check) = registry::check_update(old_dep, new_dep, registry) {
+                        if check.new_yanked {
+                            println!(
+                                "--> WARNING: {} {} is yanked but present in the lockfile",
+                                new_dep.name, new_dep.version
+                            );
+                        }
+
+                        if let (Some(old_publisher), Some(new_publisher)) =
+                            (&check.old_release_publisher, &check.new_release_publisher)
+                        {
+                            if old_publisher != new_publisher {
+                                println!(
+                                    "--> Release publisher changed: {} {} was published by {}, {} {} by {}",
+                                    old_dep.name,
+                                    old_dep.version,
+                                    old_publisher,
+                                    new_dep.name,
+                                    new_dep.version,
+                                    new_publisher
+                                );
+                            }
+                        }
+                    }
+                }
             }
         }
 
@@ -254,12 +434,36 @@ impl Display for Op {
             Op::Add(dep) => write!(fmt, "+++ {} {}", dep.name, dep.version),
             Op::Remove(dep) => write!(fmt, "--- {} {}", dep.name, dep.version),
             Op::Update(old, new) => {
-                write!(fmt, "    {} {} -> {}", old.name, old.version, new.version)
+                let bump = Bump::classify(&old.version, &new.version);
+                write!(
+                    fmt,
+                    "    {} {} -> {} [{}]",
+                    old.name, old.version, new.version, bump
+                )
             }
+            Op::Move {
+                name,
+                version,
+                old_source,
+                new_source,
+            } => write!(
+                fmt,
+                "~~~ {} {}: {} -> {}",
+                name,
+                version,
+                source_display(old_source.as_ref()),
+                source_display(new_source.as_ref()),
+            ),
         }
     }
 }
 
+fn source_display(source: Option<&SourceId>) -> String {
+    source
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "<none>".to_owned())
+}
+
 fn wrap_op(op: fn(Dep) -> Op, desp: Vec<Dep>) -> impl Iterator<Item = Op> {
     desp.into_iter().map(op)
 }
@@ -280,6 +484,30 @@ fn find_vers_diff(old: Vec<Dep>, new: Vec<Dep>) -> impl Iterator<Item = Op> {
         new.remove(&u);
     }
 
+    // Same version, but the source changed (eg. crates.io -> git, or a
+    // path/vendored move). Report these explicitly instead of letting them
+    // fall through as an unrelated remove+add pair.
+    let moved = old
+        .iter()
+        .filter_map(|o| {
+            new.iter()
+                .find(|n| n.version == o.version)
+                .map(|n| (o.clone(), n.clone()))
+        })
+        .collect::<Vec<_>>();
+
+    for (o, n) in &moved {
+        old.remove(o);
+        new.remove(n);
+    }
+
+    let moved = moved.into_iter().map(|(old, new)| Op::Move {
+        name: old.name,
+        version: old.version,
+        old_source: old.source,
+        new_source: new.source,
+    });
+
     let mut old = old.into_iter().collect::<Vec<_>>();
     let mut new = new.into_iter().collect::<Vec<_>>();
 
@@ -291,7 +519,7 @@ fn find_vers_diff(old: Vec<Dep>, new: Vec<Dep>) -> impl Iterator<Item = Op> {
     let removed = removed.into_iter().map(Op::Remove);
     let added = added.into_iter().map(Op::Add);
 
-    removed.chain(common).chain(added)
+    moved.chain(removed).chain(common).chain(added)
 }
 
 #[derive(Error, Debug)]
@@ -315,9 +543,27 @@ fn main() -> Result<(), Error> {
 
         let revspec = repo.revparse(revspec)?;
         let parent;
-
-        // FIXME: MERGE_BASE mode is not doing the right thing, probably
-        let (old_id, new_id) = if revspec.mode().is_range() {
+        let merge_base;
+
+        let (old_id, new_id) = if revspec.mode().is_merge_base() {
+            // a...b mode: compare against the merge base (the commit the branch
+            // forked from), not `from` directly. This is what reviewers mean by
+            // "what did this branch add relative to where it forked".
+            let from = revspec
+                .from()
+                .ok_or(NotSpec)
+                .context("Missing range start")?;
+            let to = revspec.to().ok_or(NotSpec).context("Missing range end")?;
+
+            let base_oid = repo
+                .merge_base(from.id(), to.id())
+                .context("Failed to find merge base")?;
+            merge_base = repo
+                .find_object(base_oid, None)
+                .context("Failed to load merge base commit")?;
+
+            (Some(&merge_base), Some(to))
+        } else if revspec.mode().is_range() {
             // a..b mode
             (revspec.from(), revspec.to())
         } else {
@@ -363,22 +609,40 @@ fn main() -> Result<(), Error> {
             EitherOrBoth::Right(add) => Either::Left(wrap_op(Op::Add, add.1)),
             EitherOrBoth::Both(old, new) => Either::Right(find_vers_diff(old.1, new.1)),
         })
+        .filter(|op| match op {
+            Op::Update(old, new) => {
+                Bump::classify(&old.version, &new.version).passes_level(opts.level)
+            }
+            _ => true,
+        })
         .collect::<Vec<_>>();
 
-    let all_deps = ops.iter().flat_map(|op| match op {
-        Op::Add(dep) | Op::Remove(dep) => Either::Left(iter::once(dep)),
-        Op::Update(old, new) => Either::Right(iter::once(old).chain(iter::once(new))),
+    let all_deps = ops.iter().flat_map(|op| -> Box<dyn Iterator<Item = &Dep>> {
+        match op {
+            Op::Add(dep) | Op::Remove(dep) => Box::new(iter::once(dep)),
+            Op::Update(old, new) => Box::new(iter::once(old).chain(iter::once(new))),
+            Op::Move { .. } => Box::new(iter::empty()),
+        }
     });
 
     let config = Config::default()?;
     let resolver = Resolver::new(&config, all_deps)?;
-
-    for op in &ops {
-        println!("{}", op);
-        if opts.metadata {
-            op.print_metadata(&resolver, opts.changelog)?;
-        } else if opts.changelog {
-            op.print_changelog(&resolver)?;
+    let registry = opts.registry_check.then(registry::RegistryClient::new);
+
+    match opts.format {
+        Format::Human => {
+            for op in &ops {
+                println!("{}", op);
+                if opts.metadata {
+                    op.print_metadata(&resolver, opts.changelog, registry.as_ref())?;
+                } else if opts.changelog {
+                    op.print_changelog(&resolver)?;
+                }
+            }
+        }
+        Format::Json => {
+            let report = output::build_report(&ops, &resolver, opts.changelog, registry.as_ref())?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
         }
     }
 
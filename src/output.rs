@@ -0,0 +1,195 @@
+//! Machine-readable representation of a diff, for `--format json`.
+//!
+//! This mirrors what the human `Display` impl on `Op` and `Op::print_metadata`
+//! render, but as serde-serializable structs instead of `println!` calls, so a
+//! PR bot or policy gate can consume the diff programmatically. Based on how
+//! cargo's own `print_lockfile_changes` separates computing the change set
+//! from rendering it.
+
+use std::collections::BTreeSet;
+
+use anyhow::Error;
+use serde::Serialize;
+
+use super::{changelog_diff, get_changelog, Bump, Dep, Op};
+use crate::registry::{self, RegistryClient};
+use crate::sources::Resolver;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Kind {
+    Add,
+    Remove,
+    Update,
+    Move,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Metadata {
+    pub build_script_added: Option<bool>,
+    pub proc_macro_added: Option<bool>,
+    pub license_old: Option<String>,
+    pub license_new: Option<String>,
+    pub added_authors: Vec<String>,
+    pub changelog_additions: Option<String>,
+    /// Whether the new version is yanked on crates.io. Only set with
+    /// `--registry-check`, when crates.io had data for the crate.
+    pub yanked: Option<bool>,
+    /// The crates.io release publishers (the account `cargo publish` ran as)
+    /// of the old and new version of an update, as far as `--registry-check`
+    /// could learn them. Both are set whether or not they differ. This is not
+    /// the crate's owner set — see `registry` module docs.
+    pub release_publisher_old: Option<String>,
+    pub release_publisher_new: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Entry {
+    pub kind: Kind,
+    pub name: String,
+    pub old_version: Option<String>,
+    pub new_version: Option<String>,
+    pub old_source: Option<String>,
+    pub new_source: Option<String>,
+    /// Bump severity (eg. `"major"`, `"downgrade"`), only set for updates.
+    pub bump: Option<String>,
+    pub metadata: Metadata,
+}
+
+fn add_metadata(
+    dep: &Dep,
+    resolver: &Resolver,
+    registry: Option<&RegistryClient>,
+) -> Result<Metadata, Error> {
+    let mut metadata = Metadata::default();
+
+    if let Some(pkg) = resolver.pkg(dep)? {
+        metadata.build_script_added = Some(pkg.has_custom_build());
+        metadata.proc_macro_added = Some(pkg.proc_macro());
+    }
+
+    if let Some(registry) = registry {
+        if let Some(check) = registry::check_add(dep, registry) {
+            metadata.yanked = Some(check.yanked);
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn update_metadata(
+    old: &Dep,
+    new: &Dep,
+    resolver: &Resolver,
+    changelog: bool,
+    registry: Option<&RegistryClient>,
+) -> Result<Metadata, Error> {
+    let mut metadata = Metadata::default();
+
+    let old_pkg = resolver.pkg(old)?;
+    let new_pkg = resolver.pkg(new)?;
+    if let (Some(old_pkg), Some(new_pkg)) = (old_pkg, new_pkg) {
+        metadata.build_script_added =
+            Some(!old_pkg.has_custom_build() && new_pkg.has_custom_build());
+        metadata.proc_macro_added = Some(!old_pkg.proc_macro() && new_pkg.proc_macro());
+
+        let old_meta = old_pkg.manifest().metadata();
+        let new_meta = new_pkg.manifest().metadata();
+        metadata.license_old = old_meta.license.clone();
+        metadata.license_new = new_meta.license.clone();
+
+        let old_authors = old_meta.authors.iter().collect::<BTreeSet<_>>();
+        let new_authors = new_meta.authors.iter().collect::<BTreeSet<_>>();
+        metadata.added_authors = (&new_authors - &old_authors).into_iter().cloned().collect();
+
+        if changelog {
+            let old_log = get_changelog(old_pkg.root())?;
+            let new_log = get_changelog(new_pkg.root())?;
+            let diff = changelog_diff(old_log, new_log);
+            if !diff.is_empty() {
+                metadata.changelog_additions = Some(diff);
+            }
+        }
+    }
+
+    if let Some(registry) = registry {
+        if let Some(check) = registry::check_update(old, new, registry) {
+            metadata.yanked = Some(check.new_yanked);
+            metadata.release_publisher_old = check.old_release_publisher;
+            metadata.release_publisher_new = check.new_release_publisher;
+        }
+    }
+
+    Ok(metadata)
+}
+
+impl Entry {
+    fn from_op(
+        op: &Op,
+        resolver: &Resolver,
+        changelog: bool,
+        registry: Option<&RegistryClient>,
+    ) -> Result<Self, Error> {
+        let entry = match op {
+            Op::Add(dep) => Entry {
+                kind: Kind::Add,
+                name: dep.name.to_string(),
+                old_version: None,
+                new_version: Some(dep.version.to_string()),
+                old_source: None,
+                new_source: dep.source.as_ref().map(ToString::to_string),
+                bump: None,
+                metadata: add_metadata(dep, resolver, registry)?,
+            },
+            Op::Remove(dep) => Entry {
+                kind: Kind::Remove,
+                name: dep.name.to_string(),
+                old_version: Some(dep.version.to_string()),
+                new_version: None,
+                old_source: dep.source.as_ref().map(ToString::to_string),
+                new_source: None,
+                bump: None,
+                metadata: Metadata::default(),
+            },
+            Op::Update(old, new) => Entry {
+                kind: Kind::Update,
+                name: old.name.to_string(),
+                old_version: Some(old.version.to_string()),
+                new_version: Some(new.version.to_string()),
+                old_source: old.source.as_ref().map(ToString::to_string),
+                new_source: new.source.as_ref().map(ToString::to_string),
+                bump: Some(Bump::classify(&old.version, &new.version).to_string()),
+                metadata: update_metadata(old, new, resolver, changelog, registry)?,
+            },
+            Op::Move {
+                name,
+                version,
+                old_source,
+                new_source,
+            } => Entry {
+                kind: Kind::Move,
+                name: name.to_string(),
+                old_version: Some(version.to_string()),
+                new_version: Some(version.to_string()),
+                old_source: old_source.as_ref().map(ToString::to_string),
+                new_source: new_source.as_ref().map(ToString::to_string),
+                bump: None,
+                metadata: Metadata::default(),
+            },
+        };
+
+        Ok(entry)
+    }
+}
+
+/// Computes the full diff, including metadata, as serializable entries.
+pub(crate) fn build_report(
+    ops: &[Op],
+    resolver: &Resolver,
+    changelog: bool,
+    registry: Option<&RegistryClient>,
+) -> Result<Vec<Entry>, Error> {
+    ops.iter()
+        .map(|op| Entry::from_op(op, resolver, changelog, registry))
+        .collect()
+}
@@ -0,0 +1,169 @@
+//! Optional, online supply-chain checks against crates.io.
+//!
+//! Enabled with `--registry-check`. Local lockfiles/manifests can't tell us
+//! whether a version was yanked after the fact, or who actually published it
+//! — so we ask crates.io directly. One request per crate name covers every
+//! version we care about, and responses are cached for the run so a large
+//! update batch doesn't hammer the service.
+//!
+//! What we report here is the *release publisher* (the crates.io account
+//! `cargo publish` ran as for that specific version), not the crate's owner
+//! set. crates.io only exposes a crate's *current* owners
+//! (`/api/v1/crates/{name}/owners`), with no way to ask who owned it back
+//! when an old version was published, so there's no way to answer "did the
+//! owner set change between these two releases" from the API. A release
+//! publisher change is therefore not by itself evidence of a compromised or
+//! handed-off crate — many healthy, multi-maintainer crates have different
+//! people cut different releases, including CI service accounts.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use anyhow::{Context, Error};
+use serde::Deserialize;
+
+use super::Dep;
+
+const CRATES_IO_API: &str = "https://crates.io/api/v1/crates";
+const USER_AGENT: &str = concat!(
+    "cargo-depdiff/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/avast/cargo-depdiff)"
+);
+
+#[derive(Debug, Clone, Deserialize)]
+struct CrateResponse {
+    versions: Vec<VersionInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct VersionInfo {
+    num: String,
+    yanked: bool,
+    published_by: Option<Publisher>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Publisher {
+    login: String,
+}
+
+/// What we learned about one version of a crate.
+#[derive(Debug, Clone)]
+pub(crate) struct VersionCheck {
+    pub yanked: bool,
+    pub release_publisher: Option<String>,
+}
+
+/// Returns true if `source` looks like the crates.io registry.
+///
+/// The only other crates.io-shaped thing a lockfile source can point to is a
+/// mirror, which we don't special-case here.
+fn is_crates_io(source: Option<&super::SourceId>) -> bool {
+    source
+        .map(|source| source.to_string().contains("crates.io-index"))
+        .unwrap_or(false)
+}
+
+fn fetch(name: &str) -> Result<CrateResponse, Error> {
+    let url = format!("{}/{}", CRATES_IO_API, name);
+    let response = ureq::get(&url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .with_context(|| format!("Request to crates.io for {} failed", name))?;
+    response
+        .into_json()
+        .with_context(|| format!("Invalid response from crates.io for {}", name))
+}
+
+/// Caches crates.io responses (and fetch failures) by crate name for the
+/// lifetime of one run.
+pub(crate) struct RegistryClient {
+    cache: RefCell<HashMap<String, Option<CrateResponse>>>,
+}
+
+impl RegistryClient {
+    pub(crate) fn new() -> Self {
+        Self {
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn crate_info(&self, name: &str) -> Option<CrateResponse> {
+        if let Some(cached) = self.cache.borrow().get(name) {
+            return cached.clone();
+        }
+
+        let info = match fetch(name) {
+            Ok(info) => Some(info),
+            Err(err) => {
+                eprintln!("--> Registry check skipped for {}: {:#}", name, err);
+                None
+            }
+        };
+
+        self.cache
+            .borrow_mut()
+            .insert(name.to_owned(), info.clone());
+        info
+    }
+
+    fn version_check(&self, name: &str, version: &str) -> Option<VersionCheck> {
+        let info = self.crate_info(name)?;
+        let version_info = info.versions.into_iter().find(|v| v.num == version)?;
+        Some(VersionCheck {
+            yanked: version_info.yanked,
+            release_publisher: version_info.published_by.map(|p| p.login),
+        })
+    }
+}
+
+/// What we found for a newly-added dependency.
+#[derive(Debug)]
+pub(crate) struct AddCheck {
+    pub yanked: bool,
+}
+
+/// What we found for an updated dependency.
+#[derive(Debug)]
+pub(crate) struct UpdateCheck {
+    pub new_yanked: bool,
+    pub old_release_publisher: Option<String>,
+    pub new_release_publisher: Option<String>,
+}
+
+/// Looks up registry facts for a newly-added dependency. Returns `None` if
+/// the source isn't crates.io or nothing could be learned about it.
+pub(crate) fn check_add(dep: &Dep, registry: &RegistryClient) -> Option<AddCheck> {
+    if !is_crates_io(dep.source.as_ref()) {
+        return None;
+    }
+
+    registry
+        .version_check(dep.name.as_str(), &dep.version.to_string())
+        .map(|check| AddCheck {
+            yanked: check.yanked,
+        })
+}
+
+/// Looks up registry facts for an updated dependency: whether the new
+/// version is yanked, and who published each side. Returns `None` if the
+/// source isn't crates.io or nothing could be learned about either version.
+pub(crate) fn check_update(old: &Dep, new: &Dep, registry: &RegistryClient) -> Option<UpdateCheck> {
+    if !is_crates_io(new.source.as_ref()) {
+        return None;
+    }
+
+    let old_check = registry.version_check(old.name.as_str(), &old.version.to_string());
+    let new_check = registry.version_check(new.name.as_str(), &new.version.to_string());
+
+    if old_check.is_none() && new_check.is_none() {
+        return None;
+    }
+
+    Some(UpdateCheck {
+        new_yanked: new_check.as_ref().map_or(false, |check| check.yanked),
+        old_release_publisher: old_check.and_then(|check| check.release_publisher),
+        new_release_publisher: new_check.and_then(|check| check.release_publisher),
+    })
+}
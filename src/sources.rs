@@ -7,10 +7,28 @@ use cargo::core::source::SourceMap;
 use cargo::core::SourceId;
 use cargo::sources::config::SourceConfigMap;
 use cargo::util::config::Config;
+use cargo_lock::package::SourceId as LockSourceId;
 use semver::Version;
 
 use super::Dep;
 
+/// Normalizes a `Cargo.lock` source id to the form cargo itself would produce.
+///
+/// Lockfile v3 and v4 URL-encode the same registry/git source differently
+/// (query parameter order, percent-encoding, ...), so two `Dep`s that refer to
+/// the exact same source compare as unequal unless both go through this. We
+/// round-trip through cargo's own `SourceId`, which already knows how to
+/// collapse those encodings to one canonical form.
+pub(crate) fn canonicalize_source(source: &LockSourceId) -> Result<LockSourceId, Error> {
+    let canonical = SourceId::from_url(&source.to_string())
+        .with_context(|| format!("Can't parse source {}", source))?;
+    canonical
+        .as_url()
+        .to_string()
+        .parse()
+        .with_context(|| format!("Can't re-parse canonicalized source {}", canonical))
+}
+
 impl Dep {
     // Returns None in case of local dependencies/workspace stuff (not included)
     fn pkg_id(&self) -> Result<Option<PackageId>, Error> {